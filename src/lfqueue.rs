@@ -0,0 +1,450 @@
+use std::cell::RefCell;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::iter::FromIterator;
+
+/// Number of hazard pointers a single thread can hold at once. `pop` needs two
+/// (the head and its successor); `push` needs one (the tail).
+const HAZARDS_PER_THREAD: usize = 2;
+
+/// How many retired nodes a thread batches before it scans the hazard list and
+/// frees those no longer referenced by anyone.
+const SCAN_THRESHOLD: usize = 64;
+
+struct Node<T> {
+    value: Option<T>,
+    next: AtomicPtr<Node<T>>
+}
+
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>
+}
+
+/// A per-thread block of hazard-pointer slots, linked into a global list so any
+/// thread can observe every other thread's protected pointers before it frees a
+/// retired node. Records are never freed; an exiting thread clears and
+/// deactivates its record so a later thread can reuse it.
+struct HazardRecord {
+    hazards: [AtomicPtr<()>; HAZARDS_PER_THREAD],
+    active: AtomicBool,
+    next: AtomicPtr<HazardRecord>
+}
+
+static HAZARD_LIST: AtomicPtr<HazardRecord> = AtomicPtr::new(ptr::null_mut());
+
+/// A retired node awaiting reclamation: its type-erased address paired with the
+/// deleter that knows the concrete `Node<T>` to `Box::from_raw`.
+type RetiredNode = (*mut (), unsafe fn(*mut ()));
+
+/// Claim a hazard record for the calling thread, reusing a retired one if any
+/// is free, otherwise allocating a fresh record and pushing it onto the global
+/// list with a Treiber-stack CAS.
+fn acquire_record() -> *mut HazardRecord {
+    let mut rec = HAZARD_LIST.load(Ordering::Acquire);
+    while !rec.is_null() {
+        unsafe {
+            if !(*rec).active.load(Ordering::Acquire)
+                && (*rec).active.compare_exchange(
+                    false, true, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                return rec;
+            }
+            rec = (*rec).next.load(Ordering::Acquire);
+        }
+    }
+    let rec = Box::into_raw(Box::new(HazardRecord {
+        hazards: [AtomicPtr::new(ptr::null_mut()), AtomicPtr::new(ptr::null_mut())],
+        active: AtomicBool::new(true),
+        next: AtomicPtr::new(ptr::null_mut())
+    }));
+    loop {
+        let head = HAZARD_LIST.load(Ordering::Acquire);
+        unsafe { (*rec).next.store(head, Ordering::Relaxed); }
+        if HAZARD_LIST.compare_exchange(
+            head, rec, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            return rec;
+        }
+    }
+}
+
+/// Free a type-erased node pointer. Stashed alongside each retired node so the
+/// reclaiming thread (which may be handling several `Queue<T>`s) knows the
+/// concrete type to `Box::from_raw`.
+unsafe fn reclaim_node<T>(node: *mut ()) {
+    drop(Box::from_raw(node as *mut Node<T>));
+}
+
+/// The calling thread's hazard record plus its batch of retired-but-not-yet-freed
+/// nodes. Dropped when the thread exits, releasing the record for reuse.
+struct Participant {
+    record: *mut HazardRecord,
+    retired: RefCell<Vec<RetiredNode>>
+}
+
+impl Participant {
+    fn new() -> Participant {
+        Participant {
+            record: acquire_record(),
+            retired: RefCell::new(Vec::new())
+        }
+    }
+
+    /// Publish `node` in hazard slot `index` so no other thread frees it.
+    fn protect(&self, index: usize, node: *mut ()) {
+        unsafe { (*self.record).hazards[index].store(node, Ordering::Release); }
+        // Order the publish before the caller's re-read of head/tail, pairing
+        // with the fence in `scan`.
+        std::sync::atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Clear hazard slot `index` once the protected node is no longer in use.
+    fn clear(&self, index: usize) {
+        unsafe { (*self.record).hazards[index].store(ptr::null_mut(), Ordering::Release); }
+    }
+
+    /// Hand an unlinked node to this thread's deferred-free list, scanning once
+    /// the batch is large enough to amortise the cost of walking the hazard list.
+    fn retire(&self, node: *mut (), deleter: unsafe fn(*mut ())) {
+        self.retired.borrow_mut().push((node, deleter));
+        if self.retired.borrow().len() >= SCAN_THRESHOLD {
+            self.scan();
+        }
+    }
+
+    /// Free every retired node that no thread currently protects.
+    fn scan(&self) {
+        // Pairs with the fence in `protect`: either the protector's re-read
+        // sees the node unlinked, or this scan sees its hazard.
+        std::sync::atomic::fence(Ordering::SeqCst);
+        let mut hazards = Vec::new();
+        let mut rec = HAZARD_LIST.load(Ordering::Acquire);
+        while !rec.is_null() {
+            unsafe {
+                for slot in &(*rec).hazards {
+                    let hazard = slot.load(Ordering::Acquire);
+                    if !hazard.is_null() {
+                        hazards.push(hazard);
+                    }
+                }
+                rec = (*rec).next.load(Ordering::Acquire);
+            }
+        }
+        hazards.sort_unstable();
+        self.retired.borrow_mut().retain(|&(node, deleter)| {
+            if hazards.binary_search(&node).is_ok() {
+                true // still referenced; keep it for a later scan
+            } else {
+                unsafe { deleter(node); }
+                false
+            }
+        });
+    }
+}
+
+impl Drop for Participant {
+    fn drop(&mut self) {
+        // Free whatever is safe now, then release the record. Any node still
+        // protected by another thread stays in this thread's retired list,
+        // which is dropped here, so that node is never freed.
+        self.scan();
+        unsafe {
+            for slot in &(*self.record).hazards {
+                slot.store(ptr::null_mut(), Ordering::Release);
+            }
+            (*self.record).active.store(false, Ordering::Release);
+        }
+    }
+}
+
+thread_local! {
+    static PARTICIPANT: Participant = Participant::new();
+}
+
+impl<T> Node<T> {
+    /// Create a new Node<T> struct.
+    /// This is only used internally by the Queue functions.
+    fn new(v: Option<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            value: v
+        }))
+    }
+}
+
+unsafe impl<T> Sync for Queue<T> {}
+unsafe impl<T> Send for Queue<T> {}
+
+impl<T> Queue<T> {
+    /// Create a new scottqueue::lfqueue::Queue<T> struct
+    /// Starts out empty, with a single dummy Node shared by `head` and `tail`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scottqueue::lfqueue::Queue;
+    /// let q: Queue<i64> = Queue::new();
+    /// ```
+    pub fn new() -> Queue<T> {
+        let dummy : *mut Node<T> = Node::new(None);
+        Queue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+        }
+    }
+
+    /// Push a value into the Queue.
+    /// Internally this creates a new Node<T> and links it onto the tail using
+    /// the lock-free Michael & Scott enqueue loop. The tail is protected by a
+    /// hazard pointer for the duration so a concurrent dequeuer cannot free it
+    /// while it is being dereferenced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scottqueue::lfqueue::Queue;
+    /// let q: Queue<i64> = Queue::new();
+    /// q.push(12);
+    /// ```
+    pub fn push(&self, value : T) {
+        let new_node : *mut Node<T> = Node::new(Some(value));
+        PARTICIPANT.with(|p| {
+            loop {
+                let tail = self.tail.load(Ordering::Acquire);
+                p.protect(0, tail as *mut ());
+                // Re-read the tail; if it moved our hazard is stale, so retry.
+                if tail != self.tail.load(Ordering::Acquire) {
+                    continue;
+                }
+                unsafe {
+                    let next = (*tail).next.load(Ordering::Acquire);
+                    if tail == self.tail.load(Ordering::Acquire) {
+                        if next.is_null() {
+                            // Try to link the new node onto the current tail.
+                            if (*tail).next.compare_exchange(
+                                next, new_node,
+                                Ordering::Release, Ordering::Relaxed).is_ok() {
+                                // Linked; swing the tail forward and we're done.
+                                let _ = self.tail.compare_exchange(
+                                    tail, new_node,
+                                    Ordering::Release, Ordering::Relaxed);
+                                break;
+                            }
+                        } else {
+                            // Tail was lagging; help it advance and retry.
+                            let _ = self.tail.compare_exchange(
+                                tail, next,
+                                Ordering::Release, Ordering::Relaxed);
+                        }
+                    }
+                }
+            }
+            p.clear(0);
+        });
+    }
+
+    /// pop a value from the Queue.
+    /// Internally this unlinks the head's successor with the lock-free
+    /// Michael & Scott dequeue loop. The head and its successor are protected
+    /// by hazard pointers; the unlinked node goes onto a deferred-free list and
+    /// is only freed once no thread can still dereference it -- the invariant a bare
+    /// `Box::from_raw` could not uphold.
+    ///
+    ///  Examples
+    ///
+    /// ```
+    /// use scottqueue::lfqueue::Queue;
+    /// let q: Queue<i64> = Queue::new();
+    /// q.push(12);
+    /// println!("Result!: {}", q.pop().unwrap());
+    /// ```
+    pub fn pop(&self) -> Option<T> {
+        PARTICIPANT.with(|p| {
+            let result;
+            loop {
+                let head = self.head.load(Ordering::Acquire);
+                p.protect(0, head as *mut ());
+                // Re-read the head; if it moved our hazard is stale, so retry.
+                if head != self.head.load(Ordering::Acquire) {
+                    continue;
+                }
+                let tail = self.tail.load(Ordering::Acquire);
+                unsafe {
+                    let next = (*head).next.load(Ordering::Acquire);
+                    p.protect(1, next as *mut ());
+                    // Make sure head (and therefore next) is still current.
+                    if head != self.head.load(Ordering::Acquire) {
+                        continue;
+                    }
+                    if head == tail {
+                        if next.is_null() {
+                            result = None; // queue is empty
+                            break;
+                        }
+                        // Tail was lagging; help it advance and retry.
+                        let _ = self.tail.compare_exchange(
+                            tail, next,
+                            Ordering::Release, Ordering::Relaxed);
+                    } else if self.head.compare_exchange(
+                        head, next,
+                        Ordering::Release, Ordering::Relaxed).is_ok() {
+                        // Only the CAS winner owns the node; `next` is still
+                        // hazard-protected, so moving its value out here is safe
+                        // and no other consumer can observe or reclaim it.
+                        result = (*next).value.take();
+                        p.retire(head as *mut (), reclaim_node::<T>);
+                        break;
+                    }
+                }
+            }
+            p.clear(0);
+            p.clear(1);
+            result
+        })
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Queue<T> {
+        Queue::new()
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    /// Walk the node list from `head`, reclaiming every node and running the
+    /// destructor of any value still waiting in the queue, then free the
+    /// trailing dummy. Without this a dropped non-empty `Queue` would leak its
+    /// nodes and never run `T`'s destructors. Nodes already unlinked and handed
+    /// to the deferred-free lists are disjoint from the live list walked here,
+    /// so none are freed twice.
+    fn drop(&mut self) {
+        unsafe {
+            let mut current: *mut Node<T> = self.head.load(Ordering::Relaxed);
+            while !current.is_null() {
+                let boxed: Box<Node<T>> = Box::from_raw(current);
+                current = boxed.next.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Iterator for Queue<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+impl<A> FromIterator<A> for Queue<A> {
+    fn from_iter<T>(iterator: T) -> Self where T: IntoIterator<Item=A> {
+        let q = Queue::new();
+        for item in iterator {
+            q.push(item);
+        }
+        q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::collections::HashSet;
+    use std::thread;
+    use self::test::Bencher;
+
+    #[test]
+    fn test_single_item() {
+        let q: super::Queue<i64> = super::Queue::new();
+        q.push(1);
+        assert_eq!(q.pop().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_queue() {
+        let values = &vec![1, 2, 3, 4, 5];
+        let q: super::Queue<i64> = super::Queue::new();
+        for value in values {
+            q.push(*value);
+        }
+        let mut results: Vec<i64> = vec![];
+        for _ in 0..5 {
+            results.push(q.pop().unwrap());
+        }
+        assert_eq!(&results, values);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let values = &vec![1, 2, 3, 4, 5];
+        let q: super::Queue<i64> = super::Queue::new();
+        for value in values {
+            q.push(*value);
+        }
+        let results : Vec<i64> = q.collect();
+        assert_eq!(&results, values);
+    }
+
+    #[test]
+    fn test_from_iterator() {
+        let values = &vec![1, 2, 3, 4, 5];
+        let q: super::Queue<i64> = values.clone().into_iter().collect();
+        let results : Vec<i64> = q.collect();
+        assert_eq!(&results, values);
+    }
+
+    #[test]
+    fn test_threads() {
+        for _ in 0..100 {
+            _test_threads();
+        }
+    }
+
+    fn _test_threads() {
+        let (tx, rx) = channel();
+        let nthreads = 20;
+        let nmsgs = 10000;
+
+        let q = Arc::new(super::Queue::new());
+        let mut start_set = HashSet::new();
+        let mut end_set = HashSet::new();
+        for i in 0..nmsgs {
+            q.push(i);
+            start_set.insert(i);
+        }
+        for _ in 0..nthreads {
+            let tx = tx.clone();
+            let q = q.clone();
+            thread::spawn(move|| {
+                for _ in 0..(nmsgs/nthreads) {
+                    tx.send(q.pop().unwrap()).unwrap();
+                }
+                drop(tx);
+            });
+        }
+        for _ in 0..nmsgs {
+            let msg = rx.recv().unwrap();
+            assert!(!end_set.contains(&msg));
+            end_set.insert(msg);
+
+        }
+        assert_eq!(end_set, start_set);
+    }
+
+    #[bench]
+    fn push_bench(b: &mut Bencher) {
+        let q = super::Queue::new();
+        b.iter(|| q.push(0));
+    }
+
+    #[bench]
+    fn pop_bench(b: &mut Bencher) {
+        let q = super::Queue::new();
+        for i in 0..100000 {
+            q.push(i);
+        }
+        b.iter(|| q.pop());
+    }
+}
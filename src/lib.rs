@@ -0,0 +1,4 @@
+#![cfg_attr(test, feature(test))]
+
+pub mod tlqueue;
+pub mod lfqueue;
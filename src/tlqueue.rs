@@ -1,16 +1,44 @@
 use std::ptr;
-use std::sync::{Mutex};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::{Duration, Instant};
 use std::iter::FromIterator;
 
 
 struct Node<T> {
     value: Option<T>,
-    next: *mut Node<T>
+    next: AtomicPtr<Node<T>>
 }
 
 pub struct Queue<T> {
     head: Mutex<*mut Node<T>>,
-    tail: Mutex<*mut Node<T>>
+    tail: Mutex<*mut Node<T>>,
+    not_empty: Condvar,
+    selectors: Mutex<Vec<Arc<Notifier>>>
+}
+
+/// Shared wakeup channel handed to a set of queues by [`Select`]. Every
+/// registered queue pokes it from `push`, so a single `Select::wait` can sleep
+/// on one condvar and learn that *some* queue now has data.
+pub struct Notifier {
+    ready: Mutex<bool>,
+    signal: Condvar
+}
+
+impl Notifier {
+    fn new() -> Arc<Notifier> {
+        Arc::new(Notifier {
+            ready: Mutex::new(false),
+            signal: Condvar::new(),
+        })
+    }
+
+    /// Flag that data is available and wake any thread parked in `Select::wait`.
+    fn notify(&self) {
+        let mut ready = self.ready.lock().unwrap();
+        *ready = true;
+        self.signal.notify_all();
+    }
 }
 
 impl<T> Node<T> {
@@ -18,7 +46,7 @@ impl<T> Node<T> {
     /// This is only used internally by the Queue functions.
     fn new(v: Option<T>) -> *mut Node<T> {
         Box::into_raw(Box::new(Node {
-            next: ptr::null_mut(),
+            next: AtomicPtr::new(ptr::null_mut()),
             value: v
         }))
     }
@@ -42,6 +70,8 @@ impl<T> Queue<T> {
         Queue {
             head: Mutex::new(null_node),
             tail: Mutex::new(null_node),
+            not_empty: Condvar::new(),
+            selectors: Mutex::new(Vec::new()),
         }
     }
 
@@ -58,10 +88,28 @@ impl<T> Queue<T> {
     /// ```
     pub fn push(&self, value : T) {
         let new_node : *mut Node<T> = Node::new(Some(value));
-        let mut tail = self.tail.lock().unwrap();
-        unsafe {
-            (**tail).next = new_node;
-            *tail = new_node;
+        {
+            let mut tail = self.tail.lock().unwrap();
+            unsafe {
+                // Release-store the link so a consumer that acquire-loads it
+                // sees a fully-initialised node and there is no data race on
+                // `next` when the queue is empty (head == tail).
+                (**tail).next.store(new_node, Ordering::Release);
+                *tail = new_node;
+            }
+        }
+        // Wake a single blocked consumer, if any, now that an item is linked.
+        // The notification is issued under the head lock -- the same lock
+        // `pop_wait`/`pop_timeout` hold across their emptiness check and park --
+        // so it can never slip into the gap between a consumer's check and its
+        // `wait`, which would otherwise leave it sleeping on an available item.
+        {
+            let _head = self.head.lock().unwrap();
+            self.not_empty.notify_one();
+        }
+        // Poke every `Select` this queue is currently part of.
+        for notifier in self.selectors.lock().unwrap().iter() {
+            notifier.notify();
         }
     }
 
@@ -80,15 +128,190 @@ impl<T> Queue<T> {
     pub fn pop(&self) -> Option<T> {
         let mut head = self.head.lock().unwrap();
         unsafe {
-            if (**head).next.is_null() { // is queue empty?
+            let next = (**head).next.load(Ordering::Acquire);
+            if next.is_null() { // is queue empty?
                 return None;
             }
-            let value = (*(**head).next).value.take().unwrap();
+            let value = (*next).value.take().unwrap();
             let _: Box<Node<T>> = Box::from_raw(*head); // ? make sure that shit is deleted?
-            *head = (**head).next;
+            *head = next;
             return Some(value);
         }
     }
+
+    /// Dequeue a single item while holding the head lock, or return `None` and
+    /// leave the head untouched if the queue is empty. Shared by `pop_wait`/`pop_timeout` so
+    /// the check-and-remove happens atomically under the same lock the
+    /// condvar parks on.
+    fn pop_locked(&self, head: &mut *mut Node<T>) -> Option<T> {
+        unsafe {
+            let next = (**head).next.load(Ordering::Acquire);
+            if next.is_null() { // is queue empty?
+                return None;
+            }
+            let value = (*next).value.take().unwrap();
+            let _: Box<Node<T>> = Box::from_raw(*head);
+            *head = next;
+            Some(value)
+        }
+    }
+
+    /// Pop a value, blocking the calling thread until one is available.
+    /// Parks on the head condvar so callers don't have to busy-spin against
+    /// an empty queue; `push` wakes one waiter per linked item.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scottqueue::tlqueue::Queue;
+    /// let q: Queue<i64> = Queue::new();
+    /// q.push(12);
+    /// assert_eq!(q.pop_wait(), 12);
+    /// ```
+    pub fn pop_wait(&self) -> T {
+        let mut head = self.head.lock().unwrap();
+        loop {
+            if let Some(value) = self.pop_locked(&mut head) {
+                return value;
+            }
+            head = self.not_empty.wait(head).unwrap();
+        }
+    }
+
+    /// Pop a value, blocking for at most `timeout`.
+    /// Returns `Some` if an item arrives before the deadline, otherwise
+    /// `None`. Spurious condvar wakeups are absorbed by re-checking against
+    /// the deadline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use scottqueue::tlqueue::Queue;
+    /// let q: Queue<i64> = Queue::new();
+    /// assert_eq!(q.pop_timeout(Duration::from_millis(1)), None);
+    /// q.push(7);
+    /// assert_eq!(q.pop_timeout(Duration::from_millis(1)), Some(7));
+    /// ```
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut head = self.head.lock().unwrap();
+        loop {
+            if let Some(value) = self.pop_locked(&mut head) {
+                return Some(value);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            let (guard, _) = self.not_empty.wait_timeout(head, deadline - now).unwrap();
+            head = guard;
+        }
+    }
+
+    /// Return true when the queue currently holds no items.
+    /// Used by [`Select`] to scan for a ready queue without removing anything.
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.lock().unwrap();
+        unsafe { (**head).next.load(Ordering::Acquire).is_null() }
+    }
+
+    /// Attach a shared notifier so `push` can wake a `Select` waiting across
+    /// several queues. A queue may belong to any number of live `Select`s at
+    /// once, so notifiers accumulate rather than replacing one another.
+    /// Internal to `Select`.
+    fn register(&self, notifier: &Arc<Notifier>) {
+        self.selectors.lock().unwrap().push(notifier.clone());
+    }
+
+    /// Detach the notifier registered by a now-dropped `Select`, matched by
+    /// `Arc` identity so overlapping selections are left untouched.
+    fn unregister(&self, notifier: &Arc<Notifier>) {
+        self.selectors.lock().unwrap().retain(|n| !Arc::ptr_eq(n, notifier));
+    }
+}
+
+/// Block on several `Queue`s at once, waking as soon as any of them has data.
+/// This plays the role of receiver-set selection over multiple channels: build
+/// the set once, then call [`Select::wait`] repeatedly, popping from whichever
+/// queue it reports as ready.
+///
+/// # Examples
+///
+/// ```
+/// use scottqueue::tlqueue::{Queue, Select};
+/// let a: Queue<i64> = Queue::new();
+/// let b: Queue<i64> = Queue::new();
+/// let select = Select::new(vec![&a, &b]);
+/// b.push(5);
+/// let idx = select.wait();
+/// assert_eq!(idx, 1);
+/// ```
+pub struct Select<'a, T: 'a> {
+    queues: Vec<&'a Queue<T>>,
+    notifier: Arc<Notifier>
+}
+
+impl<'a, T> Select<'a, T> {
+    /// Build a selection over the given queues, registering a shared notifier
+    /// with each one up front so no queue has to be re-registered per wait.
+    pub fn new(queues: Vec<&'a Queue<T>>) -> Select<'a, T> {
+        let notifier = Notifier::new();
+        for queue in &queues {
+            queue.register(&notifier);
+        }
+        Select { queues, notifier }
+    }
+
+    /// Return the index of a queue that has data right now, or `None` if they
+    /// are all empty. Never blocks.
+    pub fn ready(&self) -> Option<usize> {
+        self.queues.iter().position(|queue| !queue.is_empty())
+    }
+
+    /// Block until one of the registered queues has data, returning its index.
+    /// The caller should then `pop` from that queue (and loop back to `wait`
+    /// if the race left it empty again). The readiness flag is consulted under
+    /// the notifier lock, so an item that arrives between the scan and the park
+    /// is not missed.
+    pub fn wait(&self) -> usize {
+        loop {
+            if let Some(index) = self.ready() {
+                return index;
+            }
+            let mut ready = self.notifier.ready.lock().unwrap();
+            while !*ready {
+                ready = self.notifier.signal.wait(ready).unwrap();
+            }
+            // Consume the flag and rescan; whoever woke us may have raced with
+            // another consumer, so `ready()` is the source of truth.
+            *ready = false;
+        }
+    }
+}
+
+impl<'a, T> Drop for Select<'a, T> {
+    fn drop(&mut self) {
+        for queue in &self.queues {
+            queue.unregister(&self.notifier);
+        }
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    /// Walk the node list from `head`, reclaiming every node and running the
+    /// destructor of any value still waiting in the queue, then free the
+    /// trailing dummy. Without this a dropped non-empty `Queue` would leak its
+    /// nodes and never run `T`'s destructors.
+    fn drop(&mut self) {
+        unsafe {
+            let mut current = *self.head.lock().unwrap();
+            while !current.is_null() {
+                let boxed: Box<Node<T>> = Box::from_raw(current);
+                current = boxed.next.load(Ordering::Relaxed);
+            }
+        }
+    }
 }
 
 impl<T> Iterator for Queue<T> {
@@ -159,6 +382,88 @@ mod tests {
         assert_eq!(&results, values);
     }
 
+    #[test]
+    fn test_pop_wait() {
+        use std::time::Duration;
+        let q = Arc::new(super::Queue::new());
+        let consumer = {
+            let q = q.clone();
+            thread::spawn(move || q.pop_wait())
+        };
+        // Give the consumer a chance to park before the item is pushed.
+        thread::sleep(Duration::from_millis(10));
+        q.push(42);
+        assert_eq!(consumer.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_pop_timeout() {
+        use std::time::Duration;
+        let q: super::Queue<i64> = super::Queue::new();
+        assert_eq!(q.pop_timeout(Duration::from_millis(5)), None);
+        q.push(99);
+        assert_eq!(q.pop_timeout(Duration::from_millis(5)), Some(99));
+    }
+
+    #[test]
+    fn test_select_ready() {
+        let a: super::Queue<i64> = super::Queue::new();
+        let b: super::Queue<i64> = super::Queue::new();
+        let select = super::Select::new(vec![&a, &b]);
+        assert_eq!(select.ready(), None);
+        b.push(5);
+        assert_eq!(select.wait(), 1);
+        assert_eq!(b.pop(), Some(5));
+    }
+
+    #[test]
+    fn test_select_blocks_until_push() {
+        use std::time::Duration;
+        let a = Arc::new(super::Queue::new());
+        let b = Arc::new(super::Queue::new());
+        let producer = {
+            let a = a.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                a.push(42);
+            })
+        };
+        let select = super::Select::new(vec![&*a, &*b]);
+        let idx = select.wait();
+        assert_eq!(idx, 0);
+        assert_eq!(a.pop(), Some(42));
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn test_drop_reclaims_values() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Counted {
+            counter: Arc<AtomicUsize>,
+        }
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        {
+            let q: super::Queue<Counted> = super::Queue::new();
+            for _ in 0..5 {
+                q.push(Counted { counter: counter.clone() });
+            }
+            // These two leave the queue and are dropped here.
+            let _ = q.pop();
+            let _ = q.pop();
+            assert_eq!(counter.load(Ordering::SeqCst), 2);
+        }
+        // Dropping the queue runs destructors for the 3 values left behind.
+        assert_eq!(counter.load(Ordering::SeqCst), 5);
+    }
+
     #[test]
     fn test_threads() {
         for _ in 0..100 {
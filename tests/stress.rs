@@ -0,0 +1,109 @@
+//! Concurrent stress harness for the two queue implementations.
+//!
+//! Unlike the in-module `test_threads`, which fills the queue single-threaded
+//! before spawning consumers, this harness runs `N` producers and `M`
+//! consumers against the same `Arc<Queue<T>>` at once and asserts that the
+//! multiset of popped values exactly equals the multiset of pushed values --
+//! no duplicates, no drops. It is meant to be run under ThreadSanitizer:
+//!
+//! ```sh
+//! ./ci/tsan.sh
+//! ```
+//!
+//! The whole file is gated on `cfg(sanitize = "thread")` so an ordinary
+//! `cargo test` skips it; the deterministic in-module tests cover the
+//! single-threaded behaviour.
+#![feature(cfg_sanitize)]
+#![cfg(sanitize = "thread")]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+/// Minimal surface shared by both queue implementations so the harness can
+/// drive either one through the same code path.
+trait Concurrent<T>: Sync + Send {
+    fn create() -> Self;
+    fn push(&self, value: T);
+    fn pop(&self) -> Option<T>;
+}
+
+impl<T> Concurrent<T> for scottqueue::tlqueue::Queue<T> {
+    fn create() -> Self { scottqueue::tlqueue::Queue::new() }
+    fn push(&self, value: T) { scottqueue::tlqueue::Queue::push(self, value) }
+    fn pop(&self) -> Option<T> { scottqueue::tlqueue::Queue::pop(self) }
+}
+
+impl<T> Concurrent<T> for scottqueue::lfqueue::Queue<T> {
+    fn create() -> Self { scottqueue::lfqueue::Queue::new() }
+    fn push(&self, value: T) { scottqueue::lfqueue::Queue::push(self, value) }
+    fn pop(&self) -> Option<T> { scottqueue::lfqueue::Queue::pop(self) }
+}
+
+/// Run `nproducers` producers and `nconsumers` consumers against a fresh
+/// queue, each producer pushing `per_producer` distinct values, and assert
+/// that every pushed value comes back out exactly once.
+fn hammer<Q: Concurrent<u64> + 'static>(nproducers: u64, nconsumers: u64, per_producer: u64) {
+    let total = nproducers * per_producer;
+    let q: Arc<Q> = Arc::new(Q::create());
+    let produced = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = channel();
+
+    let mut producers = Vec::new();
+    for p in 0..nproducers {
+        let q = q.clone();
+        producers.push(thread::spawn(move || {
+            for i in 0..per_producer {
+                // Encode (producer, index) into a unique value.
+                q.push(p * per_producer + i);
+            }
+        }));
+    }
+
+    let mut consumers = Vec::new();
+    for _ in 0..nconsumers {
+        let q = q.clone();
+        let produced = produced.clone();
+        let tx = tx.clone();
+        consumers.push(thread::spawn(move || {
+            // Keep popping until exactly `total` values have been handed out
+            // across all consumers, spinning past transient `None`s while
+            // producers are still running.
+            while produced.load(Ordering::Acquire) < total as usize {
+                if let Some(value) = q.pop() {
+                    produced.fetch_add(1, Ordering::AcqRel);
+                    tx.send(value).unwrap();
+                }
+            }
+        }));
+    }
+    drop(tx);
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    for consumer in consumers {
+        consumer.join().unwrap();
+    }
+
+    let mut counts: HashMap<u64, u64> = HashMap::new();
+    for value in rx.iter() {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    assert_eq!(counts.len() as u64, total, "wrong number of distinct values popped");
+    for value in 0..total {
+        assert_eq!(counts.get(&value), Some(&1), "value {} was dropped or duplicated", value);
+    }
+}
+
+#[test]
+fn stress_tlqueue() {
+    hammer::<scottqueue::tlqueue::Queue<u64>>(8, 8, 10000);
+}
+
+#[test]
+fn stress_lfqueue() {
+    hammer::<scottqueue::lfqueue::Queue<u64>>(8, 8, 10000);
+}